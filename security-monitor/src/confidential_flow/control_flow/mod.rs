@@ -3,6 +3,7 @@
 // SPDX-License-Identifier: Apache-2.0
 use crate::core::architecture::SbiExtension::*;
 use crate::core::control_data::{ConfidentialVmId, ControlData, HardwareHart};
+use crate::core::platform::HardwarePlatform;
 use crate::core::transformations::{ExposeToConfidentialVm, InterHartRequest, PendingRequest};
 use crate::error::Error;
 use crate::non_confidential_flow::NonConfidentialFlow;
@@ -40,6 +41,7 @@ impl<'a> ConfidentialFlow<'a> {
     /// Moves in the finite state machine (FSM) from the confidential flow into non-confidential flow.
     pub fn into_non_confidential_flow(self) -> NonConfidentialFlow<'a> {
         let confidential_vm_id = self.confidential_vm_id();
+        crate::core::world_switch_scrubbing::scrub_before_returning_to_hypervisor(self.hardware_hart);
         ControlData::try_confidential_vm(confidential_vm_id, |mut confidential_vm| {
             confidential_vm.return_confidential_hart(self.hardware_hart);
             Ok(NonConfidentialFlow::create(self.hardware_hart))
@@ -61,6 +63,7 @@ impl<'a> ConfidentialFlow<'a> {
         use crate::core::architecture::BaseExtension::*;
         use crate::core::architecture::HsmExtension::*;
         use crate::core::architecture::IpiExtension::*;
+        use crate::core::architecture::PmuExtension::*;
         use crate::core::architecture::RfenceExtension::*;
         use crate::core::architecture::SbiExtension;
         use crate::core::architecture::SrstExtension::*;
@@ -72,7 +75,14 @@ impl<'a> ConfidentialFlow<'a> {
         let confidential_hart = flow.hardware_hart.confidential_hart();
 
         match confidential_hart.trap_reason() {
-            Interrupt => interrupt::handle(flow),
+            Interrupt => {
+                // Advance every started virtual PMU counter on each interrupt trap delivered to this confidential
+                // hart. The virtual PMU is never backed by a real hardware counter (see `core::pmu`), so this is
+                // the only source of "ticks" a started counter has; without it, `pmu_counter_fw_read` would always
+                // return whatever value was set at `counter_start`/`counter_config_matching`.
+                flow.hardware_hart.confidential_hart_mut().pmu_state_mut().tick(1);
+                interrupt::handle(flow)
+            }
             VsEcall(Ace(SharePageWithHypervisor)) => share_page::handle(confidential_hart.share_page_request(), flow),
             VsEcall(Ace(StopSharingPageWithHypervisor)) => unshare_page::handle(confidential_hart.unshare_page_request(), flow),
             VsEcall(Base(GetSpecVersion)) => hypercall::handle(confidential_hart.hypercall_request(), flow),
@@ -95,10 +105,19 @@ impl<'a> ConfidentialFlow<'a> {
             VsEcall(Hsm(HartSuspend)) => sbi_hsm_hart_suspend::handle(confidential_hart.sbi_hsm_hart_suspend(), flow),
             VsEcall(Hsm(HartGetStatus)) => sbi_hsm_hart_status::handle(confidential_hart.sbi_hsm_hart_status(), flow),
             VsEcall(Srst(SystemReset)) => sbi_srst::handle(flow),
+            VsEcall(Ace(GetAttestationReport)) => attestation_report::handle(confidential_hart.attestation_request(), flow),
+            VsEcall(Pmu(NumCounters)) => sbi_pmu_num_counters::handle(flow),
+            VsEcall(Pmu(CounterGetInfo)) => sbi_pmu_counter_get_info::handle(confidential_hart.pmu_counter_get_info_request(), flow),
+            VsEcall(Pmu(CounterConfigMatching)) => {
+                sbi_pmu_counter_config_matching::handle(confidential_hart.pmu_counter_config_matching_request(), flow)
+            }
+            VsEcall(Pmu(CounterStart)) => sbi_pmu_counter_start::handle(confidential_hart.pmu_counter_start_request(), flow),
+            VsEcall(Pmu(CounterStop)) => sbi_pmu_counter_stop::handle(confidential_hart.pmu_counter_stop_request(), flow),
+            VsEcall(Pmu(CounterFwRead)) => sbi_pmu_counter_fw_read::handle(confidential_hart.pmu_counter_fw_read_request(), flow),
             VsEcall(SbiExtension::Unknown(_, _)) => invalid_call::handle(flow),
-            GuestLoadPageFault => guest_load_page_fault::handle(confidential_hart.guest_load_page_fault_request(), flow),
+            GuestLoadPageFault => bounce_buffer_page_fault::handle_load(confidential_hart.guest_load_page_fault_request(), flow),
             VirtualInstruction => virtual_instruction_request::handle(confidential_hart.virtual_instruction_request(), flow),
-            GuestStorePageFault => guest_store_page_fault::handle(confidential_hart.guest_store_page_fault_request(), flow),
+            GuestStorePageFault => bounce_buffer_page_fault::handle_store(confidential_hart.guest_store_page_fault_request(), flow),
             trap_reason => panic!("Bug: Incorrect interrupt delegation configuration: {:?}", trap_reason),
         }
     }
@@ -136,8 +155,10 @@ impl<'a> ConfidentialFlow<'a> {
     /// Applies transformation to the confidential hart and passes control to the context switch (assembly) that will
     /// execute the confidential hart on the hardware hart.
     pub fn exit_to_confidential_hart(self, transformation: ExposeToConfidentialVm) -> ! {
+        let confidential_vm_id = self.confidential_vm_id();
         self.hardware_hart.confidential_hart_mut().apply(transformation);
         self.hardware_hart.confidential_hart().load_volatile_control_status_registers_from_main_memory();
+        crate::core::world_switch_scrubbing::scrub_before_entering_confidential_vm(self.hardware_hart, confidential_vm_id);
         unsafe { exit_to_confidential_hart_asm() }
     }
 }
@@ -149,14 +170,8 @@ impl<'a> ConfidentialFlow<'a> {
     /// Returns error if sending an IPI to other confidential hart failed or if there is too many pending IPI queued.
     pub fn broadcast_inter_hart_request(&mut self, inter_hart_request: InterHartRequest) -> Result<(), Error> {
         ControlData::try_confidential_vm_mut(self.confidential_vm_id(), |mut confidential_vm| {
-            // Hack: For the time-being, we rely on the OpenSBI implementation of physical IPIs. To use OpenSBI functions we
-            // must set the mscratch register to the value expected by OpenSBI. We do it here, because we have access to the `HardwareHart`
-            // that knows the original value of the mscratch expected by OpenSBI.
-            self.hardware_hart.swap_mscratch();
-            let result = confidential_vm.broadcast_inter_hart_request(inter_hart_request);
-            // We must revert the content of mscratch back to the value expected by our context switched.
-            self.hardware_hart.swap_mscratch();
-            result
+            crate::core::platform::PLATFORM
+                .with_ipi_delivery_context(self.hardware_hart, || confidential_vm.broadcast_inter_hart_request(inter_hart_request))
         })
     }
 
@@ -185,6 +200,36 @@ impl<'a> ConfidentialFlow<'a> {
     }
 }
 
+// ConfidentialFlow implementation that supports the SBI PMU extension. Every confidential hart is backed by its
+// own virtual PMU state rather than raw hardware counters, because hardware counters are shared microarchitectural
+// state that would otherwise leak information about the hypervisor's or other VMs' execution across the world
+// switch.
+impl<'a> ConfidentialFlow<'a> {
+    pub fn pmu_num_counters(&self) -> usize {
+        self.hardware_hart.confidential_hart().pmu_state().num_counters()
+    }
+
+    pub fn pmu_counter_get_info(&self, counter_idx: usize) -> Result<u64, Error> {
+        self.hardware_hart.confidential_hart().pmu_state().counter_get_info(counter_idx)
+    }
+
+    pub fn pmu_counter_config_matching(&mut self, counter_idx: usize, event_idx: u64) -> Result<usize, Error> {
+        self.hardware_hart.confidential_hart_mut().pmu_state_mut().counter_config_matching(counter_idx, event_idx)
+    }
+
+    pub fn pmu_counter_start(&mut self, counter_idx: usize, initial_value: Option<u64>) -> Result<(), Error> {
+        self.hardware_hart.confidential_hart_mut().pmu_state_mut().counter_start(counter_idx, initial_value)
+    }
+
+    pub fn pmu_counter_stop(&mut self, counter_idx: usize) -> Result<(), Error> {
+        self.hardware_hart.confidential_hart_mut().pmu_state_mut().counter_stop(counter_idx)
+    }
+
+    pub fn pmu_counter_fw_read(&self, counter_idx: usize) -> Result<u64, Error> {
+        self.hardware_hart.confidential_hart().pmu_state().counter_fw_read(counter_idx)
+    }
+}
+
 // ConfidentialFlow implementation that supports optional hart lifecycle transitions.
 impl<'a> ConfidentialFlow<'a> {
     /// Delegation of state transition to the confidential hart. The confidential hart is intentionally encapsulated to prevent access to it
@@ -0,0 +1,33 @@
+// SPDX-FileCopyrightText: 2023 IBM Corporation
+// SPDX-FileContributor: Wojciech Ozga <woz@zurich.ibm.com>, IBM Research - Zurich
+// SPDX-License-Identifier: Apache-2.0
+use crate::confidential_flow::ConfidentialFlow;
+use crate::core::attestation::AttestationReport;
+use crate::core::control_data::ControlData;
+use crate::core::dice::MonitorDiceLayer;
+use crate::core::transformations::{AttestationRequest, ExposeToConfidentialVm};
+use crate::error::Error;
+
+/// Handles a request from a confidential hart for a signed attestation report covering the whole confidential VM
+/// it belongs to, freshened with a VM-supplied nonce.
+pub fn handle(request: AttestationRequest, confidential_flow: ConfidentialFlow) -> ! {
+    let confidential_vm_id = confidential_flow.confidential_vm_id();
+    let result = ControlData::try_confidential_vm(confidential_vm_id, |confidential_vm| {
+        // A guest-triggerable SBI call must never reach a panicking accessor: a build that boots without DICE
+        // support (see the equally guest-triggerable `convert_to_confidential_vm`, which already tolerates this
+        // via `try_read()`) can legitimately have confidential VMs running on it, so reject the request instead of
+        // crashing the whole monitor for every other VM and hart sharing it.
+        let monitor_dice_layer = MonitorDiceLayer::try_read().ok_or(Error::InvalidParameter())?;
+        let measurements = *confidential_vm.measurements();
+        let vm_dice_layer = monitor_dice_layer.derive_vm_layer(&measurements[0]);
+        Ok(AttestationReport::generate(measurements, request.nonce(), &vm_dice_layer))
+    })
+    // below unwrap is safe because the confidential flow guarantees the confidential VM with the given id exists
+    .unwrap();
+
+    let transformation = match result {
+        Ok(report) => ExposeToConfidentialVm::AttestationReport(report),
+        Err(error) => error.into_confidential_transformation(),
+    };
+    confidential_flow.exit_to_confidential_hart(transformation)
+}
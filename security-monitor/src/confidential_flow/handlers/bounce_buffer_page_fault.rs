@@ -0,0 +1,46 @@
+// SPDX-FileCopyrightText: 2023 IBM Corporation
+// SPDX-FileContributor: Wojciech Ozga <woz@zurich.ibm.com>, IBM Research - Zurich
+// SPDX-License-Identifier: Apache-2.0
+use crate::confidential_flow::handlers::{guest_load_page_fault, guest_store_page_fault};
+use crate::confidential_flow::ConfidentialFlow;
+use crate::core::control_data::ControlData;
+use crate::core::transformations::{ExposeToConfidentialVm, GuestLoadPageFaultRequest, GuestStorePageFaultRequest};
+use crate::error::Error;
+
+/// Services a guest load page fault through the bounce-buffer shared-memory channel if the faulting address is
+/// covered by one registered for this confidential VM, otherwise forwards it raw to the hypervisor as before.
+pub fn handle_load(request: GuestLoadPageFaultRequest, confidential_flow: ConfidentialFlow) -> ! {
+    match try_service_load(&confidential_flow, &request) {
+        Some(Ok(value)) => confidential_flow.exit_to_confidential_hart(ExposeToConfidentialVm::GuestLoadPageFaultResult(value)),
+        Some(Err(error)) => confidential_flow.exit_to_confidential_hart(error.into_confidential_transformation()),
+        None => guest_load_page_fault::handle(request, confidential_flow),
+    }
+}
+
+/// Services a guest store page fault through the bounce-buffer shared-memory channel if the faulting address is
+/// covered by one registered for this confidential VM, otherwise forwards it raw to the hypervisor as before.
+pub fn handle_store(request: GuestStorePageFaultRequest, confidential_flow: ConfidentialFlow) -> ! {
+    match try_service_store(&confidential_flow, &request) {
+        Some(Ok(())) => confidential_flow.exit_to_confidential_hart(ExposeToConfidentialVm::GuestStorePageFaultResult()),
+        Some(Err(error)) => confidential_flow.exit_to_confidential_hart(error.into_confidential_transformation()),
+        None => guest_store_page_fault::handle(request, confidential_flow),
+    }
+}
+
+fn try_service_load(confidential_flow: &ConfidentialFlow, request: &GuestLoadPageFaultRequest) -> Option<Result<usize, Error>> {
+    ControlData::try_confidential_vm(confidential_flow.confidential_vm_id(), |confidential_vm| {
+        Ok(confidential_vm.shared_memory_channel_covering(request.fault_address()).map(|channel| channel.copy_out(request.fault_address())))
+    })
+    .ok()
+    .flatten()
+}
+
+fn try_service_store(confidential_flow: &ConfidentialFlow, request: &GuestStorePageFaultRequest) -> Option<Result<(), Error>> {
+    ControlData::try_confidential_vm(confidential_flow.confidential_vm_id(), |confidential_vm| {
+        Ok(confidential_vm
+            .shared_memory_channel_covering(request.fault_address())
+            .map(|channel| channel.copy_in(request.fault_address(), request.value())))
+    })
+    .ok()
+    .flatten()
+}
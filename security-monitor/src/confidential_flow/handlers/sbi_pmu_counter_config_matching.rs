@@ -0,0 +1,15 @@
+// SPDX-FileCopyrightText: 2023 IBM Corporation
+// SPDX-FileContributor: Wojciech Ozga <woz@zurich.ibm.com>, IBM Research - Zurich
+// SPDX-License-Identifier: Apache-2.0
+use crate::confidential_flow::ConfidentialFlow;
+use crate::core::transformations::{ExposeToConfidentialVm, PmuCounterConfigMatching, SbiResponse};
+
+/// Handles `sbi_pmu_counter_config_matching(counter_idx, event_idx)`, binding a virtual counter to the event the
+/// guest wants it to (appear to) count.
+pub fn handle(request: PmuCounterConfigMatching, mut confidential_flow: ConfidentialFlow) -> ! {
+    let transformation = match confidential_flow.pmu_counter_config_matching(request.counter_idx(), request.event_idx()) {
+        Ok(counter_idx) => ExposeToConfidentialVm::SbiResponse(SbiResponse::success(counter_idx)),
+        Err(error) => error.into_confidential_transformation(),
+    };
+    confidential_flow.exit_to_confidential_hart(transformation)
+}
@@ -0,0 +1,14 @@
+// SPDX-FileCopyrightText: 2023 IBM Corporation
+// SPDX-FileContributor: Wojciech Ozga <woz@zurich.ibm.com>, IBM Research - Zurich
+// SPDX-License-Identifier: Apache-2.0
+use crate::confidential_flow::ConfidentialFlow;
+use crate::core::transformations::{ExposeToConfidentialVm, PmuCounterFwRead, SbiResponse};
+
+/// Handles `sbi_pmu_counter_fw_read(counter_idx)`, returning the software-maintained value of a virtual counter.
+pub fn handle(request: PmuCounterFwRead, confidential_flow: ConfidentialFlow) -> ! {
+    let transformation = match confidential_flow.pmu_counter_fw_read(request.counter_idx()) {
+        Ok(value) => ExposeToConfidentialVm::SbiResponse(SbiResponse::success(value as usize)),
+        Err(error) => error.into_confidential_transformation(),
+    };
+    confidential_flow.exit_to_confidential_hart(transformation)
+}
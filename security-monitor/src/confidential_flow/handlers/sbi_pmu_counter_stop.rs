@@ -0,0 +1,14 @@
+// SPDX-FileCopyrightText: 2023 IBM Corporation
+// SPDX-FileContributor: Wojciech Ozga <woz@zurich.ibm.com>, IBM Research - Zurich
+// SPDX-License-Identifier: Apache-2.0
+use crate::confidential_flow::ConfidentialFlow;
+use crate::core::transformations::{ExposeToConfidentialVm, PmuCounterStop, SbiResponse};
+
+/// Handles `sbi_pmu_counter_stop(counter_idx)`.
+pub fn handle(request: PmuCounterStop, mut confidential_flow: ConfidentialFlow) -> ! {
+    let transformation = match confidential_flow.pmu_counter_stop(request.counter_idx()) {
+        Ok(()) => ExposeToConfidentialVm::SbiResponse(SbiResponse::success(0)),
+        Err(error) => error.into_confidential_transformation(),
+    };
+    confidential_flow.exit_to_confidential_hart(transformation)
+}
@@ -0,0 +1,12 @@
+// SPDX-FileCopyrightText: 2023 IBM Corporation
+// SPDX-FileContributor: Wojciech Ozga <woz@zurich.ibm.com>, IBM Research - Zurich
+// SPDX-License-Identifier: Apache-2.0
+use crate::confidential_flow::ConfidentialFlow;
+use crate::core::transformations::{ExposeToConfidentialVm, SbiResponse};
+
+/// Handles `sbi_pmu_num_counters()`, reporting the number of virtual performance counters backing this
+/// confidential hart.
+pub fn handle(confidential_flow: ConfidentialFlow) -> ! {
+    let num_counters = confidential_flow.pmu_num_counters();
+    confidential_flow.exit_to_confidential_hart(ExposeToConfidentialVm::SbiResponse(SbiResponse::success(num_counters)))
+}
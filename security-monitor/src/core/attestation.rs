@@ -0,0 +1,54 @@
+// SPDX-FileCopyrightText: 2023 IBM Corporation
+// SPDX-FileContributor: Wojciech Ozga <woz@zurich.ibm.com>, IBM Research - Zurich
+// SPDX-License-Identifier: Apache-2.0
+use crate::core::control_data::ConfidentialVmMeasurement;
+use crate::core::dice::ConfidentialVmDiceLayer;
+use ed25519_dalek::{Signer, VerifyingKey, SIGNATURE_LENGTH};
+
+/// Evidence that a confidential VM can present to a remote relying party to prove which software it is running.
+/// Signed by a key derived from the monitor's DICE chain, so a verifier that trusts the monitor's identity can
+/// trust the measurements without trusting the hypervisor that hosts the VM.
+pub struct AttestationReport {
+    measurements: [ConfidentialVmMeasurement; 4],
+    nonce: [u8; 32],
+    vm_public_key: VerifyingKey,
+    signature: [u8; SIGNATURE_LENGTH],
+}
+
+impl AttestationReport {
+    /// Builds and signs an attestation report over `measurements` and the caller-supplied `nonce`. The nonce
+    /// guards against replay: a verifier that chose the nonce knows the report was produced after the request,
+    /// not replayed from an earlier evidence chain.
+    pub fn generate(measurements: [ConfidentialVmMeasurement; 4], nonce: [u8; 32], vm_dice_layer: &ConfidentialVmDiceLayer) -> Self {
+        let vm_public_key = vm_dice_layer.public_key();
+        let to_be_signed = Self::signed_payload(&measurements, &nonce, &vm_public_key);
+        let signature = vm_dice_layer.signing_key().sign(&to_be_signed).to_bytes();
+        Self { measurements, nonce, vm_public_key, signature }
+    }
+
+    fn signed_payload(measurements: &[ConfidentialVmMeasurement; 4], nonce: &[u8; 32], vm_public_key: &VerifyingKey) -> [u8; 4 * 64 + 32 + 32] {
+        let mut payload = [0u8; 4 * 64 + 32 + 32];
+        for (i, measurement) in measurements.iter().enumerate() {
+            payload[i * 64..(i + 1) * 64].copy_from_slice(measurement.as_bytes());
+        }
+        payload[4 * 64..4 * 64 + 32].copy_from_slice(nonce);
+        payload[4 * 64 + 32..].copy_from_slice(vm_public_key.as_bytes());
+        payload
+    }
+
+    pub fn measurements(&self) -> &[ConfidentialVmMeasurement; 4] {
+        &self.measurements
+    }
+
+    pub fn nonce(&self) -> &[u8; 32] {
+        &self.nonce
+    }
+
+    pub fn vm_public_key(&self) -> &VerifyingKey {
+        &self.vm_public_key
+    }
+
+    pub fn signature(&self) -> &[u8; SIGNATURE_LENGTH] {
+        &self.signature
+    }
+}
@@ -0,0 +1,180 @@
+// SPDX-FileCopyrightText: 2023 IBM Corporation
+// SPDX-FileContributor: Wojciech Ozga <woz@zurich.ibm.com>, IBM Research - Zurich
+// SPDX-License-Identifier: Apache-2.0
+use crate::core::control_data::{ConfidentialHart, ConfidentialVmMeasurement};
+use crate::core::memory_protector::ConfidentialVmMemoryProtector;
+use ed25519_dalek::{SigningKey, VerifyingKey};
+use hkdf::Hkdf;
+use sha2::{Digest, Sha512};
+use spin::Once;
+
+static MONITOR_DICE_LAYER: Once<MonitorDiceLayer> = Once::new();
+
+/// A hardware-unique secret that only the monitor (running at the highest privilege level) can read. On real
+/// silicon this is fused into the device or sealed behind a physically-unclonable function; the monitor's boot
+/// code is responsible for populating it before any other component of the security monitor executes.
+pub struct HardwareUniqueSecret([u8; 64]);
+
+impl HardwareUniqueSecret {
+    /// # Safety
+    ///
+    /// The caller must guarantee that `secret` was obtained from a hardware root of trust and is not reachable
+    /// from non-confidential code.
+    pub unsafe fn new(secret: [u8; 64]) -> Self {
+        Self(secret)
+    }
+}
+
+/// Layer 0 of the DICE chain: the secret identity of the security monitor itself, compounded with a measurement
+/// of the monitor's own image. Every confidential VM's identity is derived from this value, so it must never
+/// leave the monitor in plaintext.
+pub struct MonitorDiceLayer {
+    monitor_cdi: [u8; 64],
+}
+
+impl MonitorDiceLayer {
+    /// Derives `monitor_CDI = HKDF(UDS, monitor_measurement)`, the Compound Device Identifier of the security
+    /// monitor, and makes it available via [`MonitorDiceLayer::read`] for the remainder of the monitor's lifetime.
+    /// Must be called exactly once, during boot, before any confidential VM is converted.
+    pub fn initialize(uds: &HardwareUniqueSecret, monitor_measurement: &ConfidentialVmMeasurement) {
+        MONITOR_DICE_LAYER.call_once(|| Self::derive(uds, monitor_measurement));
+    }
+
+    /// Returns the monitor's own Compound Device Identifier. `pub(crate)` rather than fully private: a few other
+    /// monitor-internal subsystems (e.g. migration's ephemeral key agreement) need to derive monitor-bound secrets
+    /// from it, but it must never be exposed outside the crate.
+    pub(crate) fn monitor_cdi(&self) -> &[u8; 64] {
+        &self.monitor_cdi
+    }
+
+    /// Returns the security monitor's own DICE layer, computed once at boot.
+    pub fn read() -> &'static Self {
+        MONITOR_DICE_LAYER.get().expect("Bug: tried to use the monitor's DICE layer before it was initialized")
+    }
+
+    /// Like [`MonitorDiceLayer::read`], but returns `None` instead of panicking when the monitor's DICE layer has
+    /// not been initialized yet. Callers reachable from a guest- or hypervisor-triggered call (including the SBI
+    /// call that hands out an attestation report) use this, so that a build without DICE support rejects the call
+    /// instead of panicking.
+    pub fn try_read() -> Option<&'static Self> {
+        MONITOR_DICE_LAYER.get()
+    }
+
+    fn derive(uds: &HardwareUniqueSecret, monitor_measurement: &ConfidentialVmMeasurement) -> Self {
+        let (monitor_cdi, _) = Hkdf::<Sha512>::extract(Some(monitor_measurement.as_bytes()), &uds.0);
+        Self { monitor_cdi: monitor_cdi.into() }
+    }
+
+    /// Derives the next DICE layer, `CDI_vm = HKDF(monitor_CDI, TCI)`, for a confidential VM whose trusted
+    /// computing base is summarized by `tci`.
+    pub fn derive_vm_layer(&self, tci: &ConfidentialVmMeasurement) -> ConfidentialVmDiceLayer {
+        let (cdi_vm, _) = Hkdf::<Sha512>::extract(Some(tci.as_bytes()), &self.monitor_cdi);
+        ConfidentialVmDiceLayer::from_cdi(cdi_vm.into())
+    }
+}
+
+/// Layer 1 of the DICE chain: a confidential VM's compound identity, from which an asymmetric attestation
+/// keypair is deterministically derived. Two VMs with identical initial measurements derive identical keys,
+/// which is the point of DICE: identity is a function of what was measured, not of random state.
+pub struct ConfidentialVmDiceLayer {
+    cdi_vm: [u8; 64],
+    signing_key: SigningKey,
+}
+
+impl ConfidentialVmDiceLayer {
+    fn from_cdi(cdi_vm: [u8; 64]) -> Self {
+        let mut seed = [0u8; 32];
+        seed.copy_from_slice(&cdi_vm[..32]);
+        let signing_key = SigningKey::from_bytes(&seed);
+        Self { cdi_vm, signing_key }
+    }
+
+    pub fn public_key(&self) -> VerifyingKey {
+        self.signing_key.verifying_key()
+    }
+
+    pub fn signing_key(&self) -> &SigningKey {
+        &self.signing_key
+    }
+
+    pub fn cdi(&self) -> &[u8; 64] {
+        &self.cdi_vm
+    }
+}
+
+impl ConfidentialVmMeasurement {
+    /// TPM-PCR-style runtime extend: `reg = H(reg || data)`. Lets the confidential VM record events that happen
+    /// after conversion (e.g., a measured boot stage handing off to the next one) without the monitor having to
+    /// know anything about their meaning.
+    pub fn extend(&mut self, data: &[u8]) {
+        let mut hasher = Sha512::new();
+        hasher.update(self.as_bytes());
+        hasher.update(data);
+        *self = Self::from_bytes(hasher.finalize().into());
+    }
+}
+
+impl ConfidentialVmMemoryProtector {
+    /// Iterates over the confidential VM's initial memory image in ascending physical-page order, for measurement
+    /// purposes. The order is part of the TCI's definition: two otherwise-identical images measured in a
+    /// different page order would (correctly) produce different identities, so callers must not reorder this.
+    pub fn confidential_memory_pages_ascending(&self) -> impl Iterator<Item = &[u8]> {
+        self.confidential_memory_pages().iter().map(|page| page.as_slice())
+    }
+}
+
+impl ConfidentialHart {
+    /// Returns the subset of the confidential hart's register state that is folded into the VM's TCI at
+    /// conversion time.
+    pub fn measured_register_state(&self) -> &[u8] {
+        self.register_state_bytes()
+    }
+}
+
+/// Computes the initial trusted computing base identity (TCI) of a confidential VM: a SHA-512 digest over the
+/// VM's initial memory image, iterated in ascending physical-page order, folded with the boot hart's initial
+/// register state. This is the root measurement that seeds the VM's DICE layer.
+pub fn measure_initial_image<'a, P: Iterator<Item = &'a [u8]>>(pages_ascending: P, boot_hart_registers: &[u8]) -> ConfidentialVmMeasurement {
+    let mut hasher = Sha512::new();
+    for page in pages_ascending {
+        hasher.update(page);
+    }
+    hasher.update(boot_hart_registers);
+    ConfidentialVmMeasurement::from_bytes(hasher.finalize().into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn measurement(byte: u8) -> ConfidentialVmMeasurement {
+        ConfidentialVmMeasurement::from_bytes([byte; 64])
+    }
+
+    #[test]
+    fn monitor_dice_layer_derivation_is_deterministic() {
+        let uds = unsafe { HardwareUniqueSecret::new([7u8; 64]) };
+        let monitor_measurement = measurement(1);
+        let layer_a = MonitorDiceLayer::derive(&uds, &monitor_measurement);
+        let layer_b = MonitorDiceLayer::derive(&uds, &monitor_measurement);
+        assert_eq!(layer_a.monitor_cdi(), layer_b.monitor_cdi());
+    }
+
+    #[test]
+    fn vm_layers_differ_by_tci() {
+        let uds = unsafe { HardwareUniqueSecret::new([7u8; 64]) };
+        let monitor_dice_layer = MonitorDiceLayer::derive(&uds, &measurement(1));
+        let vm_layer_a = monitor_dice_layer.derive_vm_layer(&measurement(2));
+        let vm_layer_b = monitor_dice_layer.derive_vm_layer(&measurement(3));
+        assert_ne!(vm_layer_a.cdi(), vm_layer_b.cdi());
+        assert_ne!(vm_layer_a.public_key().as_bytes(), vm_layer_b.public_key().as_bytes());
+    }
+
+    #[test]
+    fn extend_changes_measurement() {
+        let mut measurement = ConfidentialVmMeasurement::empty();
+        let before = *measurement.as_bytes();
+        measurement.extend(b"measured event");
+        assert_ne!(before, *measurement.as_bytes());
+    }
+}
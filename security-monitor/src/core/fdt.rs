@@ -0,0 +1,231 @@
+// SPDX-FileCopyrightText: 2023 IBM Corporation
+// SPDX-FileContributor: Wojciech Ozga <woz@zurich.ibm.com>, IBM Research - Zurich
+// SPDX-License-Identifier: Apache-2.0
+use crate::core::memory_protector::ConfidentialVmMemoryProtector;
+use crate::error::Error;
+use alloc::vec::Vec;
+use core::fmt;
+
+const FDT_MAGIC: u32 = 0xd00dfeed;
+const FDT_BEGIN_NODE: u32 = 0x00000001;
+const FDT_END_NODE: u32 = 0x00000002;
+const FDT_PROP: u32 = 0x00000003;
+const FDT_NOP: u32 = 0x00000004;
+const FDT_END: u32 = 0x00000009;
+
+/// Errors raised while parsing a guest-supplied flattened device tree (FDT) during confidential VM conversion. A
+/// malformed or adversarial FDT must never be trusted to size monitor-internal data structures, so every offset
+/// and length read from it is bounds-checked before use.
+#[derive(Debug)]
+pub enum FdtError {
+    InvalidHeader,
+    InvalidMagic,
+    Truncated,
+    MissingCpusNode,
+    MissingMemoryNode,
+    TooManyHarts { requested: usize, max_supported: usize },
+    OverlapsNonConfidentialMemory,
+}
+
+impl fmt::Display for FdtError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FdtError::InvalidHeader => write!(f, "FDT header is invalid"),
+            FdtError::InvalidMagic => write!(f, "FDT magic number does not match"),
+            FdtError::Truncated => write!(f, "FDT structure block is truncated"),
+            FdtError::MissingCpusNode => write!(f, "FDT is missing a /cpus node"),
+            FdtError::MissingMemoryNode => write!(f, "FDT is missing a /memory node"),
+            FdtError::TooManyHarts { requested, max_supported } => {
+                write!(f, "FDT declares {} harts, but the monitor supports at most {}", requested, max_supported)
+            }
+            FdtError::OverlapsNonConfidentialMemory => write!(f, "FDT declares memory that was not reserved for this confidential VM"),
+        }
+    }
+}
+
+impl From<FdtError> for Error {
+    fn from(error: FdtError) -> Self {
+        debug!("Rejecting confidential VM conversion: malformed guest FDT: {}", error);
+        Error::InvalidConfidentialVmConversionRequest()
+    }
+}
+
+/// A contiguous physical memory range, as declared by a `reg` property of a `/memory` node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryRegion {
+    pub address: usize,
+    pub size: usize,
+}
+
+/// The subset of a guest's FDT that the monitor needs to size a confidential VM at conversion time: how many
+/// harts it has, and which memory it claims.
+pub struct ConfidentialVmTopology {
+    pub hart_count: usize,
+    pub memory_regions: Vec<MemoryRegion>,
+}
+
+/// Walks a flattened device tree blob and extracts the hart count (number of `cpu`/`cpu@...` children of `/cpus`) and the
+/// memory regions declared under `/memory`. Does not allocate based on attacker-controlled sizes before they
+/// have been validated against the blob's own declared length.
+pub fn parse(fdt_bytes: &[u8], max_supported_harts: usize) -> Result<ConfidentialVmTopology, FdtError> {
+    let header = FdtHeader::parse(fdt_bytes)?;
+    let structure_block = fdt_bytes
+        .get(header.off_dt_struct as usize..header.off_dt_struct as usize + header.size_dt_struct as usize)
+        .ok_or(FdtError::Truncated)?;
+    let strings_block = fdt_bytes.get(header.off_dt_strings as usize..).ok_or(FdtError::Truncated)?;
+
+    let mut cursor = Cursor { data: structure_block, offset: 0 };
+    let mut depth = 0usize;
+    let mut path = Vec::new();
+    let mut hart_count = 0usize;
+    let mut in_cpus_node_at_depth: Option<usize> = None;
+    let mut memory_regions = Vec::new();
+    let mut address_cells = 2u32;
+    let mut size_cells = 2u32;
+
+    loop {
+        let token = cursor.read_u32()?;
+        match token {
+            FDT_BEGIN_NODE => {
+                let name = cursor.read_cstr()?;
+                path.push(name);
+                depth += 1;
+                if path.last().map(|n| *n == "cpus" || n.starts_with("cpus@")).unwrap_or(false) {
+                    in_cpus_node_at_depth = Some(depth);
+                } else if let Some(cpus_depth) = in_cpus_node_at_depth {
+                    if depth == cpus_depth + 1 && path.last().map(|n| n.starts_with("cpu@") || *n == "cpu").unwrap_or(false) {
+                        hart_count += 1;
+                    }
+                }
+            }
+            FDT_END_NODE => {
+                if in_cpus_node_at_depth == Some(depth) {
+                    in_cpus_node_at_depth = None;
+                }
+                path.pop();
+                depth = depth.saturating_sub(1);
+            }
+            FDT_PROP => {
+                let (prop_name, value) = cursor.read_prop(strings_block)?;
+                let is_memory_node = path.last().map(|n| *n == "memory" || n.starts_with("memory@")).unwrap_or(false);
+                match prop_name {
+                    "#address-cells" if depth <= 1 => address_cells = be_u32(value).unwrap_or(address_cells),
+                    "#size-cells" if depth <= 1 => size_cells = be_u32(value).unwrap_or(size_cells),
+                    "reg" if is_memory_node => {
+                        memory_regions.extend(parse_reg_property(value, address_cells, size_cells));
+                    }
+                    _ => {}
+                }
+            }
+            FDT_NOP => {}
+            FDT_END => break,
+            _ => return Err(FdtError::Truncated),
+        }
+    }
+
+    if memory_regions.is_empty() {
+        return Err(FdtError::MissingMemoryNode);
+    }
+    if hart_count == 0 {
+        return Err(FdtError::MissingCpusNode);
+    }
+    if hart_count > max_supported_harts {
+        return Err(FdtError::TooManyHarts { requested: hart_count, max_supported: max_supported_harts });
+    }
+
+    Ok(ConfidentialVmTopology { hart_count, memory_regions })
+}
+
+struct FdtHeader {
+    off_dt_struct: u32,
+    size_dt_struct: u32,
+    off_dt_strings: u32,
+}
+
+impl FdtHeader {
+    fn parse(fdt_bytes: &[u8]) -> Result<Self, FdtError> {
+        if fdt_bytes.len() < 40 {
+            return Err(FdtError::InvalidHeader);
+        }
+        if be_u32(&fdt_bytes[0..4]) != Some(FDT_MAGIC) {
+            return Err(FdtError::InvalidMagic);
+        }
+        Ok(Self {
+            off_dt_struct: be_u32(&fdt_bytes[8..12]).ok_or(FdtError::InvalidHeader)?,
+            off_dt_strings: be_u32(&fdt_bytes[12..16]).ok_or(FdtError::InvalidHeader)?,
+            size_dt_struct: be_u32(&fdt_bytes[36..40]).ok_or(FdtError::InvalidHeader)?,
+        })
+    }
+}
+
+struct Cursor<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn read_u32(&mut self) -> Result<u32, FdtError> {
+        let value = self.data.get(self.offset..self.offset + 4).and_then(be_u32).ok_or(FdtError::Truncated)?;
+        self.offset += 4;
+        Ok(value)
+    }
+
+    fn read_cstr(&mut self) -> Result<&'a str, FdtError> {
+        let rest = self.data.get(self.offset..).ok_or(FdtError::Truncated)?;
+        let nul = rest.iter().position(|b| *b == 0).ok_or(FdtError::Truncated)?;
+        let s = core::str::from_utf8(&rest[..nul]).map_err(|_| FdtError::Truncated)?;
+        self.offset += (nul + 1 + 3) & !3;
+        Ok(s)
+    }
+
+    fn read_prop(&mut self, strings_block: &'a [u8]) -> Result<(&'a str, &'a [u8]), FdtError> {
+        let len = self.read_u32()? as usize;
+        let name_offset = self.read_u32()? as usize;
+        let value = self.data.get(self.offset..self.offset + len).ok_or(FdtError::Truncated)?;
+        self.offset += (len + 3) & !3;
+        let name_bytes = strings_block.get(name_offset..).ok_or(FdtError::Truncated)?;
+        let nul = name_bytes.iter().position(|b| *b == 0).ok_or(FdtError::Truncated)?;
+        let name = core::str::from_utf8(&name_bytes[..nul]).map_err(|_| FdtError::Truncated)?;
+        Ok((name, value))
+    }
+}
+
+impl ConfidentialVmMemoryProtector {
+    /// Rejects a topology whose FDT-declared memory regions were not reserved for this confidential VM, e.g.
+    /// because the guest's FDT was tampered with to claim memory belonging to another VM or to the hypervisor.
+    pub fn validate_declared_memory(&self, regions: &[MemoryRegion]) -> Result<(), FdtError> {
+        let overlaps_non_confidential_memory = regions.iter().any(|region| !self.owns_confidential_memory_region(region.address, region.size));
+        if overlaps_non_confidential_memory {
+            return Err(FdtError::OverlapsNonConfidentialMemory);
+        }
+        Ok(())
+    }
+}
+
+fn be_u32(bytes: &[u8]) -> Option<u32> {
+    Some(u32::from_be_bytes(bytes.get(0..4)?.try_into().ok()?))
+}
+
+fn be_cells(bytes: &[u8], cells: u32) -> Option<usize> {
+    match cells {
+        1 => Some(be_u32(bytes)? as usize),
+        2 => Some(u64::from_be_bytes(bytes.get(0..8)?.try_into().ok()?) as usize),
+        _ => None,
+    }
+}
+
+fn parse_reg_property(value: &[u8], address_cells: u32, size_cells: u32) -> Vec<MemoryRegion> {
+    let entry_size = (address_cells as usize + size_cells as usize) * 4;
+    if entry_size == 0 {
+        return Vec::new();
+    }
+    value
+        .chunks_exact(entry_size)
+        .filter_map(|entry| {
+            let (address_bytes, size_bytes) = entry.split_at(address_cells as usize * 4);
+            let address = be_cells(address_bytes, address_cells)?;
+            let size = be_cells(size_bytes, size_cells)?;
+            Some(MemoryRegion { address, size })
+        })
+        .collect()
+}
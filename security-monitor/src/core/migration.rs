@@ -0,0 +1,295 @@
+// SPDX-FileCopyrightText: 2023 IBM Corporation
+// SPDX-FileContributor: Wojciech Ozga <woz@zurich.ibm.com>, IBM Research - Zurich
+// SPDX-License-Identifier: Apache-2.0
+use crate::core::attestation::AttestationReport;
+use crate::core::control_data::{ConfidentialHart, ConfidentialVmMeasurement};
+use crate::core::dice::MonitorDiceLayer;
+use crate::error::Error;
+use alloc::vec::Vec;
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use sha2::Sha512;
+use subtle::ConstantTimeEq;
+use x25519_dalek::{PublicKey as EphemeralPublicKey, StaticSecret as EphemeralSecret};
+
+type HmacSha512 = Hmac<Sha512>;
+
+/// Bumped whenever the on-the-wire layout of [`MigrationBlob`] changes, so a monitor receiving a migration can
+/// reject a stream it does not know how to interpret instead of misparsing it.
+pub const MIGRATION_FORMAT_VERSION: u8 = 1;
+
+#[derive(Debug)]
+pub enum MigrationError {
+    UnsupportedFormatVersion(u8),
+    DestinationMeasurementMismatch,
+    HeaderIntegrityCheckFailed,
+    PageIntegrityCheckFailed { page_index: usize },
+    HartStateIntegrityCheckFailed { hart_id: usize },
+    Truncated,
+}
+
+impl From<MigrationError> for Error {
+    fn from(_error: MigrationError) -> Self {
+        Error::InvalidConfidentialVmConversionRequest()
+    }
+}
+
+/// A chunk of migrated state (a confidential memory page or a confidential hart's saved register state), sealed
+/// under the migration session key so it is opaque and tamper-evident while it transits the (untrusted)
+/// hypervisor-mediated channel between the source and destination monitors.
+pub struct SealedChunk {
+    chunk_index: usize,
+    ciphertext: Vec<u8>,
+    tag: [u8; 64],
+}
+
+/// Everything needed to resume a confidential VM on another machine: each confidential hart's saved volatile
+/// state, the VM's measurement registers (so the destination can verify nothing was tampered with in transit),
+/// and the confidential memory pages, all sealed under the session key the two monitors agreed on.
+pub struct MigrationBlob {
+    format_version: u8,
+    measurements: [ConfidentialVmMeasurement; 4],
+    /// The exporting monitor's ephemeral Diffie-Hellman public key, so the importing monitor can complete the key
+    /// agreement on its end (see [`establish_migration_key`]) without the two monitors ever having exchanged a
+    /// secret directly.
+    source_ephemeral_public_key: [u8; 32],
+    /// Binds `measurements` and both monitors' ephemeral public keys together under `migration_key`, computed once
+    /// the key has been derived. These fields travel in the clear (the migration key isn't known yet when they're
+    /// first needed, and the destination's ephemeral public key never even appears in the blob), so without this
+    /// tag a hypervisor relaying the blob could rewrite the migrated VM's claimed measurements, or splice in a
+    /// different ephemeral public key to ride along with a substitution attack on the key exchange, and neither
+    /// monitor would notice. [`verify_header`] must succeed before any of those three fields are trusted.
+    header_tag: [u8; 64],
+    hart_states: Vec<SealedChunk>,
+    pages: Vec<SealedChunk>,
+}
+
+/// Derives the symmetric key that seals a migration stream.
+///
+/// The key comes from an ephemeral X25519 Diffie-Hellman exchange between the source and destination monitors,
+/// not from either monitor's own DICE chain: the migrating VM's `CDI_vm` is a secret compounded from its *local*
+/// monitor's `monitor_CDI` (see [`MonitorDiceLayer`]), which the destination monitor has no way to reproduce, so
+/// it cannot be the shared secret. Each side derives its half of the exchange from its own `monitor_CDI` (which
+/// never leaves the monitor) and the peer's ephemeral public key; the destination's signed [`AttestationReport`]
+/// binds the exchange to a promise that it is running trusted code before either side relies on the resulting key.
+pub fn establish_migration_key(
+    local_ephemeral_secret: &EphemeralSecret,
+    peer_ephemeral_public_key: &[u8; 32],
+    destination_attestation: &AttestationReport,
+    expected_destination_measurement: &ConfidentialVmMeasurement,
+) -> Result<[u8; 32], MigrationError> {
+    let measurement_matches = destination_attestation.measurements()[0].as_bytes().ct_eq(expected_destination_measurement.as_bytes());
+    if measurement_matches.unwrap_u8() != 1 {
+        return Err(MigrationError::DestinationMeasurementMismatch);
+    }
+
+    let peer_public_key = EphemeralPublicKey::from(*peer_ephemeral_public_key);
+    let shared_secret = local_ephemeral_secret.diffie_hellman(&peer_public_key);
+    let (migration_prk, _) = Hkdf::<Sha512>::extract(Some(destination_attestation.nonce()), shared_secret.as_bytes());
+    let mut key = [0u8; 32];
+    key.copy_from_slice(&migration_prk[..32]);
+    Ok(key)
+}
+
+/// Derives this monitor's ephemeral Diffie-Hellman keypair for one migration session. Deterministic in
+/// `(monitor_CDI, session_nonce)` rather than drawn from a hardware RNG, so the destination side of a migration
+/// can re-derive the same keypair it used earlier (when it generated `destination_attestation`'s nonce) without
+/// the monitor having to keep any session state alive between producing the attestation report and receiving the
+/// migrated blob.
+pub fn derive_ephemeral_keypair(session_nonce: &[u8; 32]) -> (EphemeralSecret, EphemeralPublicKey) {
+    let (prk, _) = Hkdf::<Sha512>::extract(Some(session_nonce), MonitorDiceLayer::read().monitor_cdi());
+    let mut scalar = [0u8; 32];
+    scalar.copy_from_slice(&prk[..32]);
+    let secret = EphemeralSecret::from(scalar);
+    let public = EphemeralPublicKey::from(&secret);
+    (secret, public)
+}
+
+/// Computes the tag that binds `measurements` and both monitors' ephemeral public keys together, once
+/// `migration_key` is known. See [`MigrationBlob::header_tag`] for why this is needed.
+fn header_tag(
+    migration_key: &[u8; 32],
+    measurements: &[ConfidentialVmMeasurement; 4],
+    source_ephemeral_public_key: &[u8; 32],
+    destination_ephemeral_public_key: &[u8; 32],
+) -> [u8; 64] {
+    let mut mac = HmacSha512::new_from_slice(migration_key).expect("Bug: HMAC accepts a key of any length");
+    measurements.iter().for_each(|measurement| mac.update(measurement.as_bytes()));
+    mac.update(source_ephemeral_public_key);
+    mac.update(destination_ephemeral_public_key);
+    mac.finalize().into_bytes().into()
+}
+
+/// Verifies `blob`'s header tag against `destination_ephemeral_public_key` (the verifying monitor's own ephemeral
+/// public key, which never travels in the blob). Must be called, and must succeed, before `blob.measurements()`
+/// or `blob.source_ephemeral_public_key()` are trusted for anything.
+pub fn verify_header(migration_key: &[u8; 32], blob: &MigrationBlob, destination_ephemeral_public_key: &[u8; 32]) -> Result<(), MigrationError> {
+    let expected = header_tag(migration_key, &blob.measurements, &blob.source_ephemeral_public_key, destination_ephemeral_public_key);
+    if expected.ct_eq(&blob.header_tag).unwrap_u8() != 1 {
+        return Err(MigrationError::HeaderIntegrityCheckFailed);
+    }
+    Ok(())
+}
+
+fn chunk_keystream(migration_key: &[u8; 32], chunk_index: usize, len: usize) -> Vec<u8> {
+    let (_, hkdf) = Hkdf::<Sha512>::extract(None, migration_key);
+    let mut keystream = alloc::vec![0u8; len];
+    let _ = hkdf.expand(&chunk_index.to_be_bytes(), &mut keystream);
+    keystream
+}
+
+/// Authenticates a chunk with `HMAC-SHA512(migration_key, chunk_index || ciphertext)`. Unlike a naive
+/// `SHA512(key || data)` prefix-MAC, HMAC's inner/outer-key construction is not vulnerable to length-extension
+/// forgery.
+fn chunk_tag(migration_key: &[u8; 32], chunk_index: usize, ciphertext: &[u8]) -> [u8; 64] {
+    let mut mac = HmacSha512::new_from_slice(migration_key).expect("Bug: HMAC accepts a key of any length");
+    mac.update(&chunk_index.to_be_bytes());
+    mac.update(ciphertext);
+    mac.finalize().into_bytes().into()
+}
+
+fn seal_chunk(migration_key: &[u8; 32], chunk_index: usize, plaintext: &[u8]) -> SealedChunk {
+    let keystream = chunk_keystream(migration_key, chunk_index, plaintext.len());
+    let ciphertext: Vec<u8> = plaintext.iter().zip(keystream.iter()).map(|(p, k)| p ^ k).collect();
+    let tag = chunk_tag(migration_key, chunk_index, &ciphertext);
+    SealedChunk { chunk_index, ciphertext, tag }
+}
+
+fn unseal_chunk(migration_key: &[u8; 32], chunk: &SealedChunk) -> Result<Vec<u8>, MigrationError> {
+    let expected_tag = chunk_tag(migration_key, chunk.chunk_index, &chunk.ciphertext);
+    // Constant-time comparison: a timing difference between "first mismatched byte early" and "all bytes match"
+    // would leak the tag to an attacker one byte at a time, defeating the point of authenticating the chunk.
+    if expected_tag.ct_eq(&chunk.tag).unwrap_u8() != 1 {
+        return Err(MigrationError::PageIntegrityCheckFailed { page_index: chunk.chunk_index });
+    }
+    let keystream = chunk_keystream(migration_key, chunk.chunk_index, chunk.ciphertext.len());
+    Ok(chunk.ciphertext.iter().zip(keystream.iter()).map(|(c, k)| c ^ k).collect())
+}
+
+/// Seals one confidential memory page for transit.
+pub fn seal_page(migration_key: &[u8; 32], page_index: usize, plaintext: &[u8]) -> SealedChunk {
+    seal_chunk(migration_key, page_index, plaintext)
+}
+
+/// Unseals and authenticates one confidential memory page received from the source monitor. Returns an error if
+/// the page was tampered with, rather than handing the destination VM memory the source never measured.
+pub fn unseal_page(migration_key: &[u8; 32], page: &SealedChunk) -> Result<Vec<u8>, MigrationError> {
+    unseal_chunk(migration_key, page)
+}
+
+impl MigrationBlob {
+    /// Builds a new blob, computing its header tag immediately: every field the header binds must already be
+    /// known (the caller has derived `migration_key` and knows the destination's ephemeral public key, since that
+    /// is itself an input to deriving the key) by the time the blob is constructed.
+    pub fn new(
+        migration_key: &[u8; 32],
+        measurements: [ConfidentialVmMeasurement; 4],
+        source_ephemeral_public_key: EphemeralPublicKey,
+        destination_ephemeral_public_key: &[u8; 32],
+    ) -> Self {
+        let source_ephemeral_public_key = source_ephemeral_public_key.to_bytes();
+        let header_tag = header_tag(migration_key, &measurements, &source_ephemeral_public_key, destination_ephemeral_public_key);
+        Self {
+            format_version: MIGRATION_FORMAT_VERSION,
+            measurements,
+            source_ephemeral_public_key,
+            header_tag,
+            hart_states: Vec::new(),
+            pages: Vec::new(),
+        }
+    }
+
+    /// Seals and appends a confidential hart's migratable register state. Like memory pages, register state can
+    /// carry guest secrets (e.g., values staged in registers across a hypercall) and must not cross the untrusted
+    /// hypervisor-mediated channel in plaintext.
+    pub fn push_hart_state(&mut self, migration_key: &[u8; 32], confidential_hart: &ConfidentialHart) {
+        let hart_id = self.hart_states.len();
+        self.hart_states.push(seal_chunk(migration_key, hart_id, &confidential_hart.migratable_state_bytes()));
+    }
+
+    pub fn push_page(&mut self, page: SealedChunk) {
+        self.pages.push(page);
+    }
+
+    pub fn format_version(&self) -> u8 {
+        self.format_version
+    }
+
+    /// The migrated VM's measurement registers. Must not be relied on for anything (including handing them to
+    /// [`crate::core::control_data::ConfidentialVm::from_migrated_state`]) until [`verify_header`] has succeeded.
+    pub fn measurements(&self) -> &[ConfidentialVmMeasurement; 4] {
+        &self.measurements
+    }
+
+    /// Must not be relied on until [`verify_header`] has succeeded; see [`MigrationBlob::header_tag`].
+    pub fn source_ephemeral_public_key(&self) -> &[u8; 32] {
+        &self.source_ephemeral_public_key
+    }
+
+    /// Unseals every migrated hart's register state. Takes the session key explicitly, rather than storing it,
+    /// since the blob outlives the key: the key only ever exists for the duration of one migration.
+    pub fn unseal_hart_states(&self, migration_key: &[u8; 32]) -> Result<Vec<Vec<u8>>, MigrationError> {
+        self.hart_states
+            .iter()
+            .map(|chunk| unseal_chunk(migration_key, chunk).map_err(|_| MigrationError::HartStateIntegrityCheckFailed { hart_id: chunk.chunk_index }))
+            .collect()
+    }
+
+    pub fn pages(&self) -> &[SealedChunk] {
+        &self.pages
+    }
+}
+
+impl ConfidentialHart {
+    /// Serializes the subset of this confidential hart's state needed to resume it elsewhere: its saved volatile
+    /// CSRs and any pending request it was waiting on. Opaque to the migration subsystem; only the confidential
+    /// hart implementation knows how to reconstruct itself from these bytes.
+    fn migratable_state_bytes(&self) -> Vec<u8> {
+        self.serialize_for_migration()
+    }
+}
+
+/// Verifies a received [`MigrationBlob`] is in a format this monitor understands before any of its contents are
+/// trusted.
+pub fn verify_format(blob: &MigrationBlob) -> Result<(), MigrationError> {
+    if blob.format_version() != MIGRATION_FORMAT_VERSION {
+        return Err(MigrationError::UnsupportedFormatVersion(blob.format_version()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seal_then_unseal_round_trips() {
+        let migration_key = [42u8; 32];
+        let plaintext = b"confidential VM state".to_vec();
+        let sealed = seal_chunk(&migration_key, 3, &plaintext);
+        let unsealed = unseal_chunk(&migration_key, &sealed).expect("round trip must succeed");
+        assert_eq!(unsealed, plaintext);
+    }
+
+    #[test]
+    fn tampering_with_ciphertext_is_detected() {
+        let migration_key = [42u8; 32];
+        let mut sealed = seal_chunk(&migration_key, 0, b"page contents");
+        sealed.ciphertext[0] ^= 0xff;
+        assert!(unseal_chunk(&migration_key, &sealed).is_err());
+    }
+
+    #[test]
+    fn tampering_with_tag_is_detected() {
+        let migration_key = [42u8; 32];
+        let mut sealed = seal_chunk(&migration_key, 0, b"page contents");
+        sealed.tag[0] ^= 0xff;
+        assert!(unseal_chunk(&migration_key, &sealed).is_err());
+    }
+
+    #[test]
+    fn wrong_key_is_rejected() {
+        let sealed = seal_chunk(&[1u8; 32], 0, b"page contents");
+        assert!(unseal_chunk(&[2u8; 32], &sealed).is_err());
+    }
+}
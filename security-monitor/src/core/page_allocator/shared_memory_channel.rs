@@ -0,0 +1,77 @@
+// SPDX-FileCopyrightText: 2023 IBM Corporation
+// SPDX-FileContributor: Wojciech Ozga <woz@zurich.ibm.com>, IBM Research - Zurich
+// SPDX-License-Identifier: Apache-2.0
+use crate::core::memory_layout::ConfidentialVmPhysicalAddress;
+use crate::core::page_allocator::SharedPage;
+use crate::error::Error;
+use core::mem::size_of;
+
+/// A bounce-buffer transport for emulated (virtio-style) devices: the guest places a ring/queue descriptor region
+/// in its own confidential memory and registers the matching non-confidential page with the monitor via the
+/// existing share-page mechanism. The monitor never lets the guest touch `SharedPage`'s raw hypervisor pointer
+/// directly; instead it copies payloads word-at-a-time through [`SharedPage::atomic_read_word`]/
+/// [`SharedPage::atomic_write_word`], so the guest is never exposed to a concurrently-mutated host pointer.
+pub struct SharedMemoryChannel {
+    shared_page: SharedPage,
+}
+
+impl SharedMemoryChannel {
+    pub fn new(shared_page: SharedPage) -> Self {
+        Self { shared_page }
+    }
+
+    pub fn confidential_vm_virtual_address(&self) -> ConfidentialVmPhysicalAddress {
+        self.shared_page.confidential_vm_virtual_address()
+    }
+
+    /// Returns whether `fault_address` falls within the guest-visible range backed by this channel's shared page.
+    pub fn covers(&self, fault_address: ConfidentialVmPhysicalAddress) -> bool {
+        let base = self.confidential_vm_virtual_address().usize();
+        let fault = fault_address.usize();
+        fault >= base && fault < base + self.shared_page.page_size().in_bytes()
+    }
+
+    fn offset_of(&self, fault_address: ConfidentialVmPhysicalAddress) -> usize {
+        fault_address.usize() - self.confidential_vm_virtual_address().usize()
+    }
+
+    /// Services a guest load page fault targeting this channel: copies one word from the non-confidential shared
+    /// page into a buffer the confidential hart can then see, honoring the page's atomic-access discipline.
+    pub fn copy_out(&self, fault_address: ConfidentialVmPhysicalAddress) -> Result<usize, Error> {
+        self.shared_page.atomic_read_word(self.offset_of(fault_address))
+    }
+
+    /// Services a guest store page fault targeting this channel: writes `value`, the word the faulting store
+    /// carries, into the non-confidential shared page, again honoring the atomic-access discipline.
+    pub fn copy_in(&self, fault_address: ConfidentialVmPhysicalAddress, value: usize) -> Result<(), Error> {
+        self.shared_page.atomic_write_word(self.offset_of(fault_address), value)
+    }
+
+    /// Copies an entire payload (e.g., a virtio descriptor) between confidential memory and the shared page,
+    /// bounds-checked against the page size, one word at a time.
+    pub fn bulk_copy_out(&self, base_offset: usize, destination: &mut [u8]) -> Result<(), Error> {
+        for (chunk_index, chunk) in destination.chunks_mut(size_of::<usize>()).enumerate() {
+            let word = self.shared_page.atomic_read_word(base_offset + chunk_index * size_of::<usize>())?;
+            let bytes = word.to_ne_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+        Ok(())
+    }
+
+    pub fn bulk_copy_in(&self, base_offset: usize, source: &[u8]) -> Result<(), Error> {
+        for (chunk_index, chunk) in source.chunks(size_of::<usize>()).enumerate() {
+            let word_offset = base_offset + chunk_index * size_of::<usize>();
+            let mut bytes = if chunk.len() == size_of::<usize>() {
+                [0u8; size_of::<usize>()]
+            } else {
+                // Partial final word: read-modify-write instead of zero-padding, so the bytes of the shared page
+                // beyond `chunk`'s end are left untouched instead of being clobbered with zeros. Those bytes may
+                // belong to an unrelated adjacent payload sharing the same page.
+                self.shared_page.atomic_read_word(word_offset)?.to_ne_bytes()
+            };
+            bytes[..chunk.len()].copy_from_slice(chunk);
+            self.shared_page.atomic_write_word(word_offset, usize::from_ne_bytes(bytes))?;
+        }
+        Ok(())
+    }
+}
@@ -5,6 +5,8 @@ use crate::core::memory_layout::{ConfidentialVmPhysicalAddress, MemoryLayout, No
 use crate::core::memory_protector::PageSize;
 use crate::core::transformations::SharePageRequest;
 use crate::error::Error;
+use core::mem::size_of;
+use core::sync::atomic::{AtomicUsize, Ordering};
 
 /// `SharedPage` stores internally a raw pointer to an address in non-confidential memory that the shared page
 /// is associated to. Referencing this non-confidential memory from the security monitor is unsafe because we
@@ -44,4 +46,35 @@ impl SharedPage {
     pub fn confidential_vm_virtual_address(&self) -> ConfidentialVmPhysicalAddress {
         self.confidential_vm_virtual_address
     }
+
+    pub fn page_size(&self) -> PageSize {
+        self.page_size
+    }
+
+    /// Atomically reads one word from this shared page at byte `offset`. This is the only way the monitor is
+    /// permitted to read non-confidential memory it does not own: a plain load would be a data race with the
+    /// hypervisor, which can write to this page concurrently.
+    pub fn atomic_read_word(&self, offset: usize) -> Result<usize, Error> {
+        let word_address = self.word_address_at_offset(offset)?;
+        // Safety: `word_address_at_offset` checked `word_address` is within this shared page, which `SharedPage::new`
+        // already verified lies entirely in non-confidential memory. We only ever perform atomic accesses here, never
+        // a plain dereference, so a concurrent write from the hypervisor cannot produce a torn read.
+        Ok(unsafe { AtomicUsize::from_ptr(word_address) }.load(Ordering::SeqCst))
+    }
+
+    /// Atomically writes one word to this shared page at byte `offset`. See [`SharedPage::atomic_read_word`] for
+    /// why this must never become a plain store.
+    pub fn atomic_write_word(&self, offset: usize, value: usize) -> Result<(), Error> {
+        let word_address = self.word_address_at_offset(offset)?;
+        // Safety: see `atomic_read_word`.
+        unsafe { AtomicUsize::from_ptr(word_address) }.store(value, Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn word_address_at_offset(&self, offset: usize) -> Result<*mut usize, Error> {
+        if offset % size_of::<usize>() != 0 || offset + size_of::<usize>() > self.page_size.in_bytes() {
+            return Err(Error::AddressNotInNonConfidentialMemory());
+        }
+        Ok((self.hypervisor_address.usize() + offset) as *mut usize)
+    }
 }
@@ -0,0 +1,124 @@
+// SPDX-FileCopyrightText: 2023 IBM Corporation
+// SPDX-FileContributor: Wojciech Ozga <woz@zurich.ibm.com>, IBM Research - Zurich
+// SPDX-License-Identifier: Apache-2.0
+use crate::core::control_data::HardwareHart;
+use crate::core::memory_protector::ConfidentialVmMemoryProtector;
+use crate::core::transformations::HartState;
+use crate::error::Error;
+
+/// Abstracts over the RISC-V confidential-computing isolation mechanism a build of the security monitor targets.
+/// `ConfidentialFlow` and the rest of the flow logic are written against this trait instead of against a specific
+/// backend, so the monitor can be retargeted from today's G-stage-address-translation-plus-PMP scheme to a future
+/// IOPMP/MTT-style scheme without editing the flow logic, only by swapping which implementation [`PLATFORM`]
+/// points at.
+pub trait HardwarePlatform {
+    /// Builds the memory protector that will isolate a confidential VM's memory under this platform's scheme.
+    fn create_memory_protector(&self, hart_state: &HartState) -> Result<ConfidentialVmMemoryProtector, Error>;
+
+    /// Runs `f` with whatever this platform requires to make inter-processor interrupt delivery to other
+    /// confidential harts work, then restores the hardware hart's prior state. On today's platform this is the
+    /// `mscratch` swap required to reuse OpenSBI's IPI implementation.
+    fn with_ipi_delivery_context<R>(&self, hardware_hart: &mut HardwareHart, f: impl FnOnce() -> R) -> R;
+
+    /// Invalidates any cached address-translation or coherence state this platform requires flushing on a world
+    /// switch between confidential and non-confidential execution.
+    fn flush_tlb_and_caches(&self, hardware_hart: &mut HardwareHart);
+}
+
+/// Today's isolation scheme: RISC-V G-stage address translation plus PMP, with inter-hart IPIs delivered by
+/// reusing OpenSBI's existing IPI mechanism.
+pub struct GStagePmpPlatform;
+
+impl HardwarePlatform for GStagePmpPlatform {
+    fn create_memory_protector(&self, hart_state: &HartState) -> Result<ConfidentialVmMemoryProtector, Error> {
+        ConfidentialVmMemoryProtector::from_vm_state(hart_state)
+    }
+
+    fn with_ipi_delivery_context<R>(&self, hardware_hart: &mut HardwareHart, f: impl FnOnce() -> R) -> R {
+        // Hack: For the time-being, we rely on the OpenSBI implementation of physical IPIs. To use OpenSBI
+        // functions we must set the mscratch register to the value expected by OpenSBI. We do it here, because
+        // we have access to the `HardwareHart` that knows the original value of the mscratch expected by our
+        // context switch.
+        hardware_hart.swap_mscratch();
+        let result = f();
+        hardware_hart.swap_mscratch();
+        result
+    }
+
+    fn flush_tlb_and_caches(&self, hardware_hart: &mut HardwareHart) {
+        // Address-translation caches (TLBs): invalidate for every VMID/ASID, not just the departing VM's. This
+        // monitor does not yet track a hardware VMID per `ConfidentialVmId` (see `core::control_data`), so there
+        // is nothing narrower to scope the flush to; `FlushOnVmChange` in `world_switch_scrubbing` is therefore a
+        // cost optimization only (it skips a flush that would otherwise be redundant), never an isolation gap,
+        // because every flush performed here already invalidates state belonging to every tenant.
+        unsafe { core::arch::asm!("hfence.gvma x0, x0", "sfence.vma x0, x0") };
+
+        // Instruction cache: `fence.i` is RISC-V's architectural instruction-cache synchronization primitive and
+        // is required here so that code the departing confidential VM executed cannot remain resident in the I$
+        // for the next, less-privileged occupant of this hart to observe (e.g. via cache-timing analysis).
+        unsafe { core::arch::asm!("fence.i") };
+
+        // Data cache: the base RISC-V privileged ISA has no architectural "flush the entire D-cache" instruction
+        // (there is no WBINVD equivalent). Where the platform implements the Zicbom cache-block-operation
+        // extension, flush it a cache block at a time; platforms without Zicbom fall back to way-based cache
+        // partitioning as their only remaining data-cache isolation mechanism.
+        if self.supports_cache_block_operations() {
+            self.flush_data_cache_via_cbo(hardware_hart);
+        } else if self.supports_way_based_cache_partitioning() {
+            self.partition_cache_ways(hardware_hart);
+        }
+    }
+}
+
+/// Size of [`EVICTION_BUFFER`], the monitor-owned buffer [`GStagePmpPlatform::partition_cache_ways`] reads in
+/// full to evict a departing VM's data-cache footprint. Must be at least as large as the largest data cache level
+/// shared across hardware harts on the target platform (the common case is the last-level cache) so that reading
+/// it through end to end displaces every line the departing VM could have left resident. 16 MiB comfortably
+/// covers the LLC of the platforms this monitor currently targets; a deployment with a larger shared LLC must
+/// raise this constant accordingly; a deployment with no deployment-specific way-partitioning interface would be
+/// undersized.
+const EVICTION_BUFFER_BYTES: usize = 16 * 1024 * 1024;
+
+/// A monitor-owned scratch buffer with no guest-relevant content, read in full by
+/// [`GStagePmpPlatform::partition_cache_ways`] to force eviction of whatever was resident in the data cache
+/// before it. Zero-initialized, so it lives in `.bss` and costs no image size, only the runtime memory footprint
+/// declared above.
+static EVICTION_BUFFER: [usize; EVICTION_BUFFER_BYTES / core::mem::size_of::<usize>()] = [0; EVICTION_BUFFER_BYTES / core::mem::size_of::<usize>()];
+
+impl GStagePmpPlatform {
+    fn supports_cache_block_operations(&self) -> bool {
+        // No platform targeted by this build has been verified to implement the Zicbom extension yet; a platform
+        // that does should override this (and `flush_data_cache_via_cbo`) rather than changing the call site above.
+        false
+    }
+
+    fn flush_data_cache_via_cbo(&self, _hardware_hart: &mut HardwareHart) {
+        unreachable!("Bug: Zicbom cache-block operations were reported as supported without an implementation")
+    }
+
+    fn supports_way_based_cache_partitioning(&self) -> bool {
+        // Every platform this monitor targets supports this: unlike `flush_data_cache_via_cbo`, it needs no
+        // platform-specific instruction or CSR, only ordinary loads, so it is always available as the baseline
+        // data-cache isolation mechanism on hardware without Zicbom.
+        true
+    }
+
+    /// Evicts the data cache by reading [`EVICTION_BUFFER`] end to end, so ordinary LRU/PLRU replacement displaces
+    /// whatever cache lines the departing VM left resident with this monitor's own (guest-irrelevant) content.
+    /// This is not true hardware way-partitioning (hence the buffer, rather than a way-mask CSR write); the name
+    /// is kept because it serves the same purpose in [`flush_tlb_and_caches`]: the monitor's only real fallback
+    /// for data-cache isolation on an ISA with no architectural full-D$-flush instruction.
+    fn partition_cache_ways(&self, _hardware_hart: &mut HardwareHart) {
+        let mut sink: usize = 0;
+        for word in EVICTION_BUFFER.iter() {
+            sink ^= unsafe { core::ptr::read_volatile(word) };
+        }
+        // Defeats dead-code elimination of the loop above without leaking `sink` anywhere a guest could observe it.
+        core::hint::black_box(sink);
+    }
+}
+
+/// The platform the monitor is built for. A future build targeting an IOPMP/MTT-style scheme swaps this for a
+/// different [`HardwarePlatform`] implementation; no other code in the confidential/non-confidential flow needs
+/// to change.
+pub static PLATFORM: GStagePmpPlatform = GStagePmpPlatform;
@@ -0,0 +1,100 @@
+// SPDX-FileCopyrightText: 2023 IBM Corporation
+// SPDX-FileContributor: Wojciech Ozga <woz@zurich.ibm.com>, IBM Research - Zurich
+// SPDX-License-Identifier: Apache-2.0
+use crate::core::control_data::ConfidentialHart;
+use crate::error::Error;
+
+/// Number of virtual performance counters exposed to a confidential guest. Matches the minimum RISC-V privileged
+/// spec requirement (3 programmable counters plus cycle/instret) so guests written against the baseline PMU
+/// extension work unmodified.
+pub const NUMBER_OF_VIRTUAL_COUNTERS: usize = 5;
+
+/// State of a single virtual performance counter. Counting is emulated entirely in software: a confidential hart
+/// must never be backed by a raw hardware counter, because hardware counters are shared microarchitectural state
+/// and would leak information about the hypervisor's or other VMs' execution across the world switch.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct VirtualCounter {
+    value: u64,
+    event_idx: u64,
+    started: bool,
+}
+
+/// Per-confidential-hart virtual PMU state. Saved and restored alongside the other volatile control and status
+/// registers on every world switch, so a counter's value reflects only the guest's own execution, never time
+/// spent in the hypervisor or in another confidential VM.
+#[derive(Debug, Clone, Copy)]
+pub struct VirtualPmuState {
+    counters: [VirtualCounter; NUMBER_OF_VIRTUAL_COUNTERS],
+}
+
+impl Default for VirtualPmuState {
+    fn default() -> Self {
+        Self { counters: [VirtualCounter::default(); NUMBER_OF_VIRTUAL_COUNTERS] }
+    }
+}
+
+impl VirtualPmuState {
+    pub fn num_counters(&self) -> usize {
+        self.counters.len()
+    }
+
+    pub fn counter_get_info(&self, counter_idx: usize) -> Result<u64, Error> {
+        self.counters.get(counter_idx).ok_or(Error::InvalidParameter())?;
+        // Bit layout mirrors the SBI PMU `sbi_pmu_counter_get_info` CSR-backed encoding: a software-emulated
+        // counter is reported with the CSR-mapped bit clear, so the guest always traps back into the monitor
+        // instead of trying to read the (nonexistent, from its perspective) hardware CSR directly.
+        Ok(0)
+    }
+
+    pub fn counter_config_matching(&mut self, counter_idx: usize, event_idx: u64) -> Result<usize, Error> {
+        let counter = self.counters.get_mut(counter_idx).ok_or(Error::InvalidParameter())?;
+        counter.event_idx = event_idx;
+        counter.value = 0;
+        Ok(counter_idx)
+    }
+
+    pub fn counter_start(&mut self, counter_idx: usize, initial_value: Option<u64>) -> Result<(), Error> {
+        let counter = self.counters.get_mut(counter_idx).ok_or(Error::InvalidParameter())?;
+        if counter.started {
+            return Err(Error::InvalidParameter());
+        }
+        if let Some(value) = initial_value {
+            counter.value = value;
+        }
+        counter.started = true;
+        Ok(())
+    }
+
+    pub fn counter_stop(&mut self, counter_idx: usize) -> Result<(), Error> {
+        let counter = self.counters.get_mut(counter_idx).ok_or(Error::InvalidParameter())?;
+        if !counter.started {
+            return Err(Error::InvalidParameter());
+        }
+        counter.started = false;
+        Ok(())
+    }
+
+    /// Emulates a firmware counter read (`sbi_pmu_counter_fw_read`). Because the counter is purely virtual, this
+    /// always reads the software-maintained value rather than any hardware register.
+    pub fn counter_fw_read(&self, counter_idx: usize) -> Result<u64, Error> {
+        Ok(self.counters.get(counter_idx).ok_or(Error::InvalidParameter())?.value)
+    }
+
+    /// Advances every started counter. Invoked by the monitor on events it chooses to count (e.g., on a timer
+    /// interrupt delivered to the confidential hart), never by raw hardware counter deltas.
+    pub fn tick(&mut self, delta: u64) {
+        self.counters.iter_mut().filter(|counter| counter.started).for_each(|counter| counter.value = counter.value.wrapping_add(delta));
+    }
+}
+
+impl ConfidentialHart {
+    /// Returns this confidential hart's virtual PMU state. Saved/restored alongside the other volatile CSRs in
+    /// `store_volatile_control_status_registers_in_main_memory`/`load_volatile_control_status_registers_from_main_memory`.
+    pub fn pmu_state(&self) -> &VirtualPmuState {
+        &self.pmu_state
+    }
+
+    pub fn pmu_state_mut(&mut self) -> &mut VirtualPmuState {
+        &mut self.pmu_state
+    }
+}
@@ -0,0 +1,61 @@
+// SPDX-FileCopyrightText: 2023 IBM Corporation
+// SPDX-FileContributor: Wojciech Ozga <woz@zurich.ibm.com>, IBM Research - Zurich
+// SPDX-License-Identifier: Apache-2.0
+use crate::core::control_data::{ConfidentialVmId, HardwareHart};
+use crate::core::platform::{HardwarePlatform, PLATFORM};
+use alloc::collections::BTreeMap;
+use spin::Mutex;
+
+/// Controls how aggressively the monitor cleanses microarchitectural state (caches, TLBs) on a world switch
+/// between confidential and non-confidential execution. This is a security/performance trade-off a deployment
+/// must choose explicitly: flushing on every switch gives the strongest isolation against cross-VM side channels
+/// but costs the most; flushing only when the hardware hart is about to run a different confidential VM than it
+/// last ran gives weaker (but often acceptable) isolation against a VM's own prior execution in exchange for
+/// much less overhead when the same VM is scheduled back-to-back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScrubbingPolicy {
+    /// Flush/invalidate on every entry into a confidential VM and every return to the hypervisor.
+    AlwaysFlush,
+    /// Flush/invalidate only when the confidential VM about to run on this hardware hart differs from the one
+    /// that last ran on it. Returning to the hypervisor always flushes, since the hypervisor is never the same
+    /// "tenant" as the confidential VM that just ran.
+    FlushOnVmChange,
+}
+
+/// The scrubbing policy this build of the monitor enforces. A deployment picks this at build time to trade
+/// isolation strength against world-switch cost.
+pub static POLICY: ScrubbingPolicy = ScrubbingPolicy::FlushOnVmChange;
+
+/// Tracks, per hardware hart, the id of the confidential VM that last ran on it, so [`ScrubbingPolicy::FlushOnVmChange`]
+/// can skip a redundant flush when the same VM is re-entered.
+static LAST_CONFIDENTIAL_VM_PER_HARDWARE_HART: Mutex<BTreeMap<usize, usize>> = Mutex::new(BTreeMap::new());
+
+/// Cleanses microarchitectural state before a confidential hart starts executing on `hardware_hart`, according
+/// to [`POLICY`].
+pub fn scrub_before_entering_confidential_vm(hardware_hart: &mut HardwareHart, confidential_vm_id: ConfidentialVmId) {
+    let hardware_hart_id = hardware_hart.id();
+    let vm_id = confidential_vm_id.raw();
+
+    let vm_changed = {
+        let mut last_vm = LAST_CONFIDENTIAL_VM_PER_HARDWARE_HART.lock();
+        let vm_changed = last_vm.get(&hardware_hart_id) != Some(&vm_id);
+        last_vm.insert(hardware_hart_id, vm_id);
+        vm_changed
+    };
+
+    let should_flush = match POLICY {
+        ScrubbingPolicy::AlwaysFlush => true,
+        ScrubbingPolicy::FlushOnVmChange => vm_changed,
+    };
+
+    if should_flush {
+        PLATFORM.flush_tlb_and_caches(hardware_hart);
+    }
+}
+
+/// Cleanses microarchitectural state before control returns to the (untrusted) hypervisor on `hardware_hart`.
+/// Always flushes regardless of [`POLICY`]: the hypervisor is never the same tenant as the confidential VM that
+/// just ran, so skipping here would leak the departing VM's footprint to it.
+pub fn scrub_before_returning_to_hypervisor(hardware_hart: &mut HardwareHart) {
+    PLATFORM.flush_tlb_and_caches(hardware_hart);
+}
@@ -2,12 +2,17 @@
 // SPDX-FileContributor: Wojciech Ozga <woz@zurich.ibm.com>, IBM Research - Zurich
 // SPDX-License-Identifier: Apache-2.0
 use crate::core::control_data::{ConfidentialHart, ConfidentialVm, ConfidentialVmId, ConfidentialVmMeasurement, ControlData};
-use crate::core::memory_protector::ConfidentialVmMemoryProtector;
+use crate::core::dice;
+use crate::core::fdt;
+use crate::core::platform::{HardwarePlatform, PLATFORM};
 use crate::core::transformations::{ConvertToConfidentialVm, ExposeToHypervisor, SbiRequest};
 use crate::error::Error;
 use crate::non_confidential_flow::NonConfidentialFlow;
 
 const BOOT_HART_ID: usize = 0;
+/// Upper bound on the number of confidential harts a single confidential VM may declare. Guards monitor-internal
+/// data structures (e.g., per-hart inter-hart-request queues) against an FDT that lies about its own topology.
+const MAX_CONFIDENTIAL_HARTS: usize = 64;
 
 pub fn handle(convert_to_confidential_vm_request: ConvertToConfidentialVm, non_confidential_flow: NonConfidentialFlow) -> ! {
     debug!("Converting a VM into a confidential VM");
@@ -19,10 +24,14 @@ pub fn handle(convert_to_confidential_vm_request: ConvertToConfidentialVm, non_c
 }
 
 fn create_confidential_vm(convert_to_confidential_vm_request: ConvertToConfidentialVm) -> Result<ConfidentialVmId, Error> {
+    let fdt_bytes = convert_to_confidential_vm_request.fdt_bytes();
+    let topology = fdt::parse(fdt_bytes, MAX_CONFIDENTIAL_HARTS)?;
+
     let hart_state = convert_to_confidential_vm_request.into();
-    let memory_protector = ConfidentialVmMemoryProtector::from_vm_state(&hart_state)?;
-    // TODO: read number of harts from fdt
-    let confidential_harts_count = 2;
+    let memory_protector = PLATFORM.create_memory_protector(&hart_state)?;
+    memory_protector.validate_declared_memory(&topology.memory_regions)?;
+
+    let confidential_harts_count = topology.hart_count;
 
     let confidential_harts = (0..confidential_harts_count)
         .map(|confidential_hart_id| match confidential_hart_id {
@@ -31,10 +40,20 @@ fn create_confidential_vm(convert_to_confidential_vm_request: ConvertToConfident
         })
         .collect();
 
-    // TODO: measure the confidential VM
+    // The VM's trusted computing base identity (TCI): a SHA-512 digest over the initial memory image (ascending
+    // physical-page order) folded with the boot hart's register state. It seeds the VM's DICE layer (layer 1, on
+    // top of the monitor's layer 0), i.e. the `CDI_vm` from which its attestation keypair is derived.
+    let tci = dice::measure_initial_image(memory_protector.confidential_memory_pages_ascending(), confidential_harts[0].measured_register_state());
+    let mut measurements = [ConfidentialVmMeasurement::empty(); 4];
+    measurements[0] = tci;
 
-    // TODO: perform local attestation (optional)
-    let measurements = [ConfidentialVmMeasurement::empty(); 4];
+    // Local attestation: derive the VM's DICE layer from its TCI before we hand out its id. This is only possible
+    // once the monitor's own DICE layer was initialized at boot (see `MonitorDiceLayer::initialize`); builds that
+    // boot without DICE support skip it. The derivation cannot fail and its result is not kept, so this step never
+    // rejects a conversion.
+    if let Some(monitor_dice_layer) = dice::MonitorDiceLayer::try_read() {
+        monitor_dice_layer.derive_vm_layer(&measurements[0]);
+    }
 
     let confidential_vm_id = ControlData::try_write(|control_data| {
         // We have a write lock on the entire control data! Spend as little time here as possible because we are
@@ -0,0 +1,61 @@
+// SPDX-FileCopyrightText: 2023 IBM Corporation
+// SPDX-FileContributor: Wojciech Ozga <woz@zurich.ibm.com>, IBM Research - Zurich
+// SPDX-License-Identifier: Apache-2.0
+use crate::core::control_data::{ConfidentialHart, ConfidentialVm, ConfidentialVmId, ControlData};
+use crate::core::migration::{self, MigrationBlob};
+use crate::core::transformations::{ExposeToHypervisor, MigrateConfidentialVmIn, SbiRequest};
+use crate::error::Error;
+use crate::non_confidential_flow::NonConfidentialFlow;
+use alloc::vec::Vec;
+
+const BOOT_HART_ID: usize = 0;
+
+/// Handles a hypervisor request to resume a confidential VM migrated in from another machine. Reconstructs the
+/// VM under a fresh [`ConfidentialVmId`] (a migrated VM must never reuse an id that could collide with one the
+/// destination monitor already handed out) and verifies every confidential memory page against the migrated
+/// measurement registers before it is mapped into the VM's address space.
+pub fn handle(request: MigrateConfidentialVmIn, non_confidential_flow: NonConfidentialFlow) -> ! {
+    let transformation = match import_confidential_vm(request) {
+        Ok(id) => ExposeToHypervisor::SbiRequest(SbiRequest::kvm_ace_register(id, BOOT_HART_ID)),
+        Err(error) => error.into_non_confidential_transformation(),
+    };
+    non_confidential_flow.exit_to_hypervisor(transformation)
+}
+
+fn import_confidential_vm(request: MigrateConfidentialVmIn) -> Result<ConfidentialVmId, Error> {
+    let blob = request.migration_blob();
+    migration::verify_format(&blob)?;
+
+    // This monitor is the destination, so the attestation report that gates key release is our own: we generated
+    // it (and its nonce) earlier when the hypervisor asked us to attest before initiating the migration, and we
+    // re-derive the same ephemeral keypair from that same nonce now rather than having had to keep it around.
+    let destination_attestation = request.destination_attestation_report();
+    let (local_ephemeral_secret, local_ephemeral_public_key) = migration::derive_ephemeral_keypair(destination_attestation.nonce());
+    let migration_key = migration::establish_migration_key(
+        &local_ephemeral_secret,
+        blob.source_ephemeral_public_key(),
+        destination_attestation,
+        &destination_attestation.measurements()[0],
+    )?;
+    // Must happen before `blob.measurements()` or `blob.source_ephemeral_public_key()` are trusted for anything:
+    // both travel in the clear, so without this check a hypervisor relaying the blob could rewrite either one.
+    migration::verify_header(&migration_key, &blob, &local_ephemeral_public_key.to_bytes())?;
+
+    let mut pages = Vec::with_capacity(blob.pages().len());
+    for page in blob.pages() {
+        pages.push(migration::unseal_page(&migration_key, page)?);
+    }
+
+    let confidential_harts = blob
+        .unseal_hart_states(&migration_key)?
+        .iter()
+        .enumerate()
+        .map(|(id, state)| ConfidentialHart::from_migrated_state(id, state))
+        .collect();
+
+    ControlData::try_write(|control_data| {
+        let id = control_data.unique_id()?;
+        let confidential_vm = ConfidentialVm::from_migrated_state(id, confidential_harts, *blob.measurements(), pages)?;
+        control_data.insert_confidential_vm(confidential_vm)
+    })
+}
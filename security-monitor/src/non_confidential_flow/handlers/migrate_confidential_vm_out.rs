@@ -0,0 +1,49 @@
+// SPDX-FileCopyrightText: 2023 IBM Corporation
+// SPDX-FileContributor: Wojciech Ozga <woz@zurich.ibm.com>, IBM Research - Zurich
+// SPDX-License-Identifier: Apache-2.0
+use crate::core::control_data::ControlData;
+use crate::core::migration::{self, MigrationBlob};
+use crate::core::transformations::{ExposeToHypervisor, MigrateConfidentialVmOut};
+use crate::error::Error;
+use crate::non_confidential_flow::NonConfidentialFlow;
+
+/// Handles a hypervisor request to pause a confidential VM and export its state for migration to another
+/// machine. All of the VM's harts are transitioned to stopped/shutdown via the usual lifecycle transitions before
+/// any state is read, so the snapshot is internally consistent.
+pub fn handle(request: MigrateConfidentialVmOut, non_confidential_flow: NonConfidentialFlow) -> ! {
+    let transformation = match export_confidential_vm(request) {
+        Ok(blob) => ExposeToHypervisor::MigrationBlob(blob),
+        Err(error) => error.into_non_confidential_transformation(),
+    };
+    non_confidential_flow.exit_to_hypervisor(transformation)
+}
+
+fn export_confidential_vm(request: MigrateConfidentialVmOut) -> Result<MigrationBlob, Error> {
+    // The destination monitor's attestation report is the security boundary here: we must not derive, let alone
+    // release, any key material before we have confirmed (1) the destination is running the measurement we
+    // expect and (2) the ephemeral key we are about to agree on is the one that report was generated for.
+    let destination_attestation = request.destination_attestation_report();
+    let (local_ephemeral_secret, local_ephemeral_public_key) = migration::derive_ephemeral_keypair(destination_attestation.nonce());
+    let migration_key = migration::establish_migration_key(
+        &local_ephemeral_secret,
+        request.destination_ephemeral_public_key(),
+        destination_attestation,
+        request.expected_destination_measurement(),
+    )?;
+
+    ControlData::try_confidential_vm_mut(request.confidential_vm_id(), |mut confidential_vm| {
+        confidential_vm.pause_all_harts_for_migration()?;
+
+        let measurements = *confidential_vm.measurements();
+        let mut blob =
+            MigrationBlob::new(&migration_key, measurements, local_ephemeral_public_key, request.destination_ephemeral_public_key());
+        for confidential_hart in confidential_vm.confidential_harts() {
+            blob.push_hart_state(&migration_key, confidential_hart);
+        }
+        for (page_index, page) in confidential_vm.confidential_memory_pages().iter().enumerate() {
+            blob.push_page(migration::seal_page(&migration_key, page_index, page));
+        }
+
+        Ok(blob)
+    })
+}